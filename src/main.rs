@@ -1,13 +1,11 @@
-use crate::node::{KVstore, Value, Encoding};
-
-mod node;
+use kv_store::node::KVstore;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Path to WAL file
     let log_file = "kvstore.log";
 
     // Open store with default magic/version
-    let mut store = KVstore::open(log_file, 0xAA, 0x01)?;
+    let store = KVstore::open(log_file, 0xAA, 0x01)?;
     println!("Initial map state: {:?}", store.map);
 
     // // ---- SET ---- (to test persistence)
@@ -1,12 +1,20 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm,
+};
+use argon2::Argon2;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use chacha20poly1305::ChaCha20Poly1305;
 use crc32fast;
+use rand::{rngs::OsRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    error::Error,
+    fmt,
     fs::{File, OpenOptions},
-    io::{BufReader, Cursor, Read, Write},
+    io::{BufReader, Cursor, Read, Seek, SeekFrom, Write},
     path::Path,
-    fmt,
-    error::Error
 };
 
 // ------------------- Errors -------------------
@@ -18,6 +26,9 @@ pub enum KVerror {
     CorruptLog,
     NotFound,
     Encoding,
+    AuthenticationFailed,
+    CheckFailed,
+    EncryptionMismatch,
 }
 
 impl fmt::Display for KVerror {
@@ -28,6 +39,13 @@ impl fmt::Display for KVerror {
             KVerror::CorruptLog => write!(f, "Log file is corrupted"),
             KVerror::NotFound => write!(f, "Key not found"),
             KVerror::Encoding => write!(f, "Invalid encoding"),
+            KVerror::AuthenticationFailed => {
+                write!(f, "AEAD authentication failed (wrong passphrase or tampered data)")
+            }
+            KVerror::CheckFailed => write!(f, "Batch compare-and-set check failed"),
+            KVerror::EncryptionMismatch => {
+                write!(f, "Requested encryption type does not match the log's header")
+            }
         }
     }
 }
@@ -42,6 +60,7 @@ pub enum Encoding {
     String = 0x00,
     Integer = 0x01,
     Float = 0x02,
+    Cbor = 0x03,
 }
 
 impl Encoding {
@@ -50,22 +69,182 @@ impl Encoding {
             0x00 => Ok(Encoding::String),
             0x01 => Ok(Encoding::Integer),
             0x02 => Ok(Encoding::Float),
+            0x03 => Ok(Encoding::Cbor),
             _ => Err(KVerror::Encoding),
         }
     }
 }
 
+// ------------------- Encryption -------------------
+
+const KDF_ARGON2ID: u8 = 0x01;
+const ENCRYPTED_HEADER_LEN: usize = 1 + 1 + 1 + 1 + 1 + 16; // magic, version, kdf_id, encryption_type, storage_type, salt
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+// An unencrypted log has no room for the fields above, so it carries only
+// this one leading byte identifying whether it's a WAL or a compacted
+// snapshot; an encrypted log folds the same byte into `LogHeader` instead.
+const PLAIN_HEADER_LEN: usize = 1;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageType {
+    // An append-only log of every set/delete/batch ever applied.
+    Wal = 0x00,
+    // The output of `KVstore::compact`: written as one set frame per live
+    // key, in its original version. Later writes append to it as to a WAL,
+    // and the storage byte stays `Compacted`.
+    Compacted = 0x01,
+}
+
+impl StorageType {
+    fn from_u8(b: u8) -> Result<StorageType, KVerror> {
+        match b {
+            0x00 => Ok(StorageType::Wal),
+            0x01 => Ok(StorageType::Compacted),
+            _ => Err(KVerror::CorruptLog),
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionType {
+    Aes256Gcm = 0x01,
+    ChaCha20Poly1305 = 0x02,
+}
+
+impl EncryptionType {
+    fn from_u8(b: u8) -> Result<EncryptionType, KVerror> {
+        match b {
+            0x01 => Ok(EncryptionType::Aes256Gcm),
+            0x02 => Ok(EncryptionType::ChaCha20Poly1305),
+            _ => Err(KVerror::CorruptLog),
+        }
+    }
+}
+
+// Per-frame AEAD state: one key, selectable cipher, fresh nonce per frame.
+struct Cipher {
+    encryption_type: EncryptionType,
+    key: [u8; 32],
+}
+
+impl Cipher {
+    fn new(encryption_type: EncryptionType, key: [u8; 32]) -> Cipher {
+        Cipher { encryption_type, key }
+    }
+
+    fn encrypt(&self, nonce_bytes: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, KVerror> {
+        let payload = Payload { msg: plaintext, aad };
+        match self.encryption_type {
+            EncryptionType::Aes256Gcm => {
+                let cipher = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(&self.key));
+                let nonce = aes_gcm::Nonce::from_slice(nonce_bytes);
+                cipher.encrypt(nonce, payload).map_err(|_| KVerror::IO)
+            }
+            EncryptionType::ChaCha20Poly1305 => {
+                let cipher = ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&self.key));
+                let nonce = chacha20poly1305::Nonce::from_slice(nonce_bytes);
+                cipher.encrypt(nonce, payload).map_err(|_| KVerror::IO)
+            }
+        }
+    }
+
+    fn decrypt(&self, nonce_bytes: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, KVerror> {
+        let payload = Payload { msg: ciphertext, aad };
+        let result = match self.encryption_type {
+            EncryptionType::Aes256Gcm => {
+                let cipher = Aes256Gcm::new(aes_gcm::Key::<Aes256Gcm>::from_slice(&self.key));
+                let nonce = aes_gcm::Nonce::from_slice(nonce_bytes);
+                cipher.decrypt(nonce, payload)
+            }
+            EncryptionType::ChaCha20Poly1305 => {
+                let cipher = ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&self.key));
+                let nonce = chacha20poly1305::Nonce::from_slice(nonce_bytes);
+                cipher.decrypt(nonce, payload)
+            }
+        };
+        result.map_err(|_| KVerror::AuthenticationFailed)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8; 16]) -> Result<[u8; 32], KVerror> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| KVerror::Startup)?;
+    Ok(key)
+}
+
+// Written once at the start of an encrypted log, before the first frame.
+struct LogHeader {
+    magic: u8,
+    version: u8,
+    kdf_id: u8,
+    encryption_type: u8,
+    storage_type: u8,
+    salt: [u8; 16],
+}
+
+impl LogHeader {
+    fn new(
+        magic: u8,
+        version: u8,
+        encryption_type: EncryptionType,
+        storage_type: StorageType,
+        salt: [u8; 16],
+    ) -> LogHeader {
+        LogHeader {
+            magic,
+            version,
+            kdf_id: KDF_ARGON2ID,
+            encryption_type: encryption_type as u8,
+            storage_type: storage_type as u8,
+            salt,
+        }
+    }
+
+    fn to_bytes(&self) -> [u8; ENCRYPTED_HEADER_LEN] {
+        let mut buf = [0u8; ENCRYPTED_HEADER_LEN];
+        buf[0] = self.magic;
+        buf[1] = self.version;
+        buf[2] = self.kdf_id;
+        buf[3] = self.encryption_type;
+        buf[4] = self.storage_type;
+        buf[5..21].copy_from_slice(&self.salt);
+        buf
+    }
+
+    fn from_bytes(buf: &[u8; ENCRYPTED_HEADER_LEN]) -> LogHeader {
+        let mut salt = [0u8; 16];
+        salt.copy_from_slice(&buf[5..21]);
+        LogHeader {
+            magic: buf[0],
+            version: buf[1],
+            kdf_id: buf[2],
+            encryption_type: buf[3],
+            storage_type: buf[4],
+            salt,
+        }
+    }
+}
+
 // ------------------- Frame -------------------
 
 #[derive(Debug)]
 pub struct Frame {
-    total_len: u32,
     magic: u8,
     version: u8,
     operation: u8,
     encoding: u8,
     key_len: u32,
     value_len: u32,
+    // Per-key version, bumped on every successful `set` of the key. It is
+    // not kept across a delete: a key set again after being deleted starts
+    // over at 1. Unused (0) on delete and batch-wrapper frames.
+    key_version: u64,
     key_bytes: Vec<u8>,
     value_bytes: Vec<u8>,
 }
@@ -77,33 +256,190 @@ pub struct Value {
     pub bytes: Vec<u8>,
 }
 
+impl Value {
+    /// Encodes `value` as CBOR via serde, for structured values that don't
+    /// fit the plain string/integer/float encodings. Pair with
+    /// `KVstore::get_as` to decode it back.
+    pub fn from_cbor<T: Serialize>(value: &T) -> Result<Value, KVerror> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).map_err(|_| KVerror::Encoding)?;
+        Ok(Value { encoding: Encoding::Cbor, bytes })
+    }
+}
+
+// ------------------- Index -------------------
+
+// Bitcask-style index entry: a pointer at the value's frame on disk rather
+// than the value itself, so RAM scales with key count, not data size.
+#[derive(Debug, Clone, Copy)]
+pub struct IndexEntry {
+    offset: u64,
+    len: u32,
+    encoding: Encoding,
+    version: u64,
+    // Set only for keys written as part of a batch: (start, len) of this
+    // key's mutation frame within the batch frame's decoded value_bytes.
+    nested: Option<(u32, u32)>,
+}
+
 // ------------------- KV Store -------------------
 
+// Snapshot of how much of the log is dead weight, so a caller can decide
+// whether `compact` is worth running.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogStats {
+    pub total_frames: u64,
+    pub live_keys: u64,
+    pub reclaimable_bytes: u64,
+}
+
 pub struct KVstore {
-    pub map: HashMap<String, Value>,
+    pub map: HashMap<String, IndexEntry>,
+    // Mirrors the keys in `map` in sorted order so `scan`/`scan_prefix` are a
+    // bounded BTree range walk instead of a full scan of `map`.
+    order: BTreeSet<String>,
     log: String,
     magic: u8,
     version: u8,
+    cipher: Option<Cipher>,
+    // Needed to rewrite an encrypted log's header during `compact`, since the
+    // key itself isn't derivable without the passphrase.
+    salt: Option<[u8; 16]>,
+    storage_type: StorageType,
+    data_file: File,
+    total_frames: u64,
+    reclaimable_bytes: u64,
+    auto_compact_threshold: Option<f64>,
+    // Per-batch-frame offset, how many of its nested keys are still live;
+    // see `release_entry`.
+    batch_live_counts: HashMap<u64, u32>,
 }
 
 impl KVstore {
     pub fn open(log: impl AsRef<Path>, magic: u8, version: u8) -> Result<KVstore, KVerror> {
         let path = log.as_ref();
-        let mut store = KVstore {
-            map: HashMap::new(),
-            log: log.as_ref().to_string_lossy().into_owned(),
-            magic,
-            version,
-        };
+        let log_path = path.to_string_lossy().into_owned();
 
         if path.exists() {
             let file = File::open(path).map_err(|_| KVerror::IO)?;
-            store = build_kv_store(file, &store.log, magic, version)?;
+            let mut reader = BufReader::new(file);
+
+            let mut storage_buf = [0u8; PLAIN_HEADER_LEN];
+            reader
+                .read_exact(&mut storage_buf)
+                .map_err(|_| KVerror::CorruptLog)?;
+            let storage_type = StorageType::from_u8(storage_buf[0])?;
+
+            build_kv_store(
+                reader,
+                &log_path,
+                OpenConfig {
+                    magic,
+                    version,
+                    cipher: None,
+                    salt: None,
+                    storage_type,
+                    header_len: PLAIN_HEADER_LEN as u64,
+                },
+            )
         } else {
-            File::create(path).map_err(|_| KVerror::IO)?;
+            let mut file = File::create(path).map_err(|_| KVerror::IO)?;
+            file.write_all(&[StorageType::Wal as u8]).map_err(|_| KVerror::IO)?;
+
+            let data_file = File::open(path).map_err(|_| KVerror::IO)?;
+            Ok(KVstore {
+                map: HashMap::new(),
+                order: BTreeSet::new(),
+                log: log_path,
+                magic,
+                version,
+                cipher: None,
+                salt: None,
+                storage_type: StorageType::Wal,
+                data_file,
+                total_frames: 0,
+                reclaimable_bytes: 0,
+                auto_compact_threshold: None,
+                batch_live_counts: HashMap::new(),
+            })
         }
+    }
+
+    /// Opens (or creates) a log whose frame bodies are encrypted at rest.
+    ///
+    /// The key is derived from `passphrase` with Argon2id using a salt that is
+    /// generated once and persisted in a header at the start of the log. On an
+    /// existing log, the stored salt is used to rebuild the same key, so
+    /// `passphrase` must match what the log was created with, and
+    /// `encryption_type` must match the cipher the log was created with
+    /// (returning `KVerror::EncryptionMismatch` otherwise) rather than being
+    /// silently ignored in favor of the stored one.
+    pub fn open_encrypted(
+        log: impl AsRef<Path>,
+        magic: u8,
+        version: u8,
+        passphrase: &str,
+        encryption_type: EncryptionType,
+    ) -> Result<KVstore, KVerror> {
+        let path = log.as_ref();
+        let log_path = path.to_string_lossy().into_owned();
+
+        if path.exists() {
+            let file = File::open(path).map_err(|_| KVerror::IO)?;
+            let mut reader = BufReader::new(file);
+
+            let mut header_buf = [0u8; ENCRYPTED_HEADER_LEN];
+            reader
+                .read_exact(&mut header_buf)
+                .map_err(|_| KVerror::CorruptLog)?;
+            let header = LogHeader::from_bytes(&header_buf);
+            let stored_encryption_type = EncryptionType::from_u8(header.encryption_type)?;
+            if stored_encryption_type != encryption_type {
+                return Err(KVerror::EncryptionMismatch);
+            }
+            let storage_type = StorageType::from_u8(header.storage_type)?;
+            let key = derive_key(passphrase, &header.salt)?;
+            let cipher = Cipher::new(stored_encryption_type, key);
 
-        Ok(store)
+            build_kv_store(
+                reader,
+                &log_path,
+                OpenConfig {
+                    magic,
+                    version,
+                    cipher: Some(cipher),
+                    salt: Some(header.salt),
+                    storage_type,
+                    header_len: ENCRYPTED_HEADER_LEN as u64,
+                },
+            )
+        } else {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            let key = derive_key(passphrase, &salt)?;
+            let cipher = Cipher::new(encryption_type, key);
+
+            let header = LogHeader::new(magic, version, encryption_type, StorageType::Wal, salt);
+            let mut file = File::create(path).map_err(|_| KVerror::IO)?;
+            file.write_all(&header.to_bytes()).map_err(|_| KVerror::IO)?;
+
+            let data_file = File::open(path).map_err(|_| KVerror::IO)?;
+            Ok(KVstore {
+                map: HashMap::new(),
+                order: BTreeSet::new(),
+                log: log_path,
+                magic,
+                version,
+                cipher: Some(cipher),
+                salt: Some(salt),
+                storage_type: StorageType::Wal,
+                data_file,
+                total_frames: 0,
+                reclaimable_bytes: 0,
+                auto_compact_threshold: None,
+                batch_live_counts: HashMap::new(),
+            })
+        }
     }
 
     fn append(&self, value: Vec<u8>) -> Result<(), KVerror> {
@@ -115,46 +451,614 @@ impl KVstore {
         log_file.write_all(&value).map_err(|_| KVerror::IO)
     }
 
+    fn current_len(&self) -> Result<u64, KVerror> {
+        std::fs::metadata(&self.log).map(|m| m.len()).map_err(|_| KVerror::IO)
+    }
+
+    // Seeks the persistent read handle to a frame's offset and decodes just
+    // that frame, instead of holding every value in memory.
+    fn read_at(&self, entry: &IndexEntry) -> Result<Value, KVerror> {
+        let mut file = self.data_file.try_clone().map_err(|_| KVerror::IO)?;
+        file.seek(SeekFrom::Start(entry.offset + 4))
+            .map_err(|_| KVerror::IO)?;
+
+        let mut frame_buf = vec![0u8; entry.len as usize];
+        file.read_exact(&mut frame_buf).map_err(|_| KVerror::CorruptLog)?;
+
+        let frame = deserialize(entry.len, frame_buf, self.cipher.as_ref())?;
+
+        match entry.nested {
+            None => Ok(Value {
+                encoding: entry.encoding,
+                bytes: frame.value_bytes,
+            }),
+            // Key was written inside a batch: `frame.value_bytes` is the
+            // batch's decoded payload, so pull this key's own mutation frame
+            // out of it and decode that (mutation frames are never
+            // separately encrypted; the batch frame's encryption already
+            // covered them).
+            Some((start, len)) => {
+                let region = &frame.value_bytes[start as usize..(start + len) as usize];
+                let mutation_len = u32::from_le_bytes(region[0..4].try_into().unwrap());
+                let mutation = deserialize(mutation_len, region[4..].to_vec(), None)?;
+                Ok(Value {
+                    encoding: entry.encoding,
+                    bytes: mutation.value_bytes,
+                })
+            }
+        }
+    }
+
     pub fn get(&self, key: &str) -> Result<Value, KVerror> {
-        self.map.get(key).cloned().ok_or(KVerror::NotFound)
+        let entry = self.map.get(key).ok_or(KVerror::NotFound)?;
+        self.read_at(entry)
+    }
+
+    /// Like `get`, but decodes the stored value as CBOR into `T`. Returns
+    /// `KVerror::Encoding` if `key` wasn't written with `Value::from_cbor`
+    /// or its bytes don't match `T`'s shape.
+    pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<T, KVerror> {
+        let value = self.get(key)?;
+        if !matches!(value.encoding, Encoding::Cbor) {
+            return Err(KVerror::Encoding);
+        }
+        ciborium::from_reader(value.bytes.as_slice()).map_err(|_| KVerror::Encoding)
+    }
+
+    /// Looks up several keys in one pass, omitting any that aren't present
+    /// instead of failing the whole call. Any other error (corrupt or
+    /// tampered frame, failed AEAD authentication) is not a "missing key"
+    /// and is propagated instead of being swallowed alongside `NotFound`.
+    pub fn get_many(&self, keys: &[&str]) -> Result<HashMap<String, Value>, KVerror> {
+        let mut out = HashMap::with_capacity(keys.len());
+        for &key in keys {
+            match self.get(key) {
+                Ok(value) => {
+                    out.insert(key.to_string(), value);
+                }
+                Err(KVerror::NotFound) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(out)
     }
 
     pub fn set(&mut self, key: &str, value: Value) -> Result<(), KVerror> {
-        let frame = Frame::new_set(key, value.clone(), self.magic, self.version);
-        let serialized = serialize(frame);
+        let encoding = value.encoding;
+        let next_version = self.map.get(key).map(|e| e.version + 1).unwrap_or(1);
+        let frame = Frame::new_set(key, value, self.magic, self.version, next_version);
+        let serialized = serialize(&frame, self.cipher.as_ref())?;
+        let frame_len = serialized.len() as u32 - 4;
 
+        let offset = self.current_len()?;
         self.append(serialized)?; // append first to maintain consistency
-        self.map.insert(key.to_string(), value);
-        Ok(())
+
+        self.total_frames += 1;
+        if let Some(old) = self.map.get(key) {
+            self.reclaimable_bytes += release_entry(old, &mut self.batch_live_counts);
+        }
+
+        self.map.insert(
+            key.to_string(),
+            IndexEntry { offset, len: frame_len, encoding, version: next_version, nested: None },
+        );
+        self.order.insert(key.to_string());
+
+        self.maybe_auto_compact()
+    }
+
+    /// Writes several keys with a single `append` syscall instead of one per
+    /// key. Like `set`, each key gets its own standalone frame and its own
+    /// bumped version; unlike `commit`, there's no atomicity or CAS
+    /// checking, just one write.
+    pub fn set_many(&mut self, entries: Vec<(String, Value)>) -> Result<(), KVerror> {
+        let mut buffer = Vec::new();
+        let mut new_entries: Vec<(String, IndexEntry)> = Vec::with_capacity(entries.len());
+        // Same trick as `commit`: track versions assigned earlier in this
+        // same call so setting a key twice in one `set_many` still bumps
+        // its version each time instead of reusing the pre-call version.
+        let mut projected_versions: HashMap<String, u64> = HashMap::new();
+        let mut offset = self.current_len()?;
+
+        for (key, value) in entries {
+            let encoding = value.encoding;
+            let current = projected_versions
+                .get(&key)
+                .copied()
+                .or_else(|| self.map.get(&key).map(|e| e.version));
+            let next_version = current.map_or(1, |v| v + 1);
+            projected_versions.insert(key.clone(), next_version);
+
+            let frame = Frame::new_set(&key, value, self.magic, self.version, next_version);
+            let serialized = serialize(&frame, self.cipher.as_ref())?;
+            let frame_len = serialized.len() as u32 - 4;
+
+            new_entries.push((
+                key,
+                IndexEntry { offset, len: frame_len, encoding, version: next_version, nested: None },
+            ));
+            offset += 4 + frame_len as u64;
+            buffer.extend_from_slice(&serialized);
+        }
+
+        self.append(buffer)?; // append first to maintain consistency
+
+        self.total_frames += new_entries.len() as u64;
+        for (key, entry) in new_entries {
+            if let Some(old) = self.map.get(&key) {
+                self.reclaimable_bytes += release_entry(old, &mut self.batch_live_counts);
+            }
+            self.order.insert(key.clone());
+            self.map.insert(key, entry);
+        }
+
+        self.maybe_auto_compact()
     }
 
     pub fn del(&mut self, key: &str) -> Result<(), KVerror> {
         let frame = Frame::new_delete(key, self.magic, self.version);
-        let serialized = serialize(frame);
+        let serialized = serialize(&frame, self.cipher.as_ref())?;
+        let frame_len = serialized.len() as u32 - 4;
 
         self.append(serialized)?; // append first
-        self.map.remove(key);
+
+        self.total_frames += 1;
+        // The tombstone itself is dead the moment it lands, plus whatever
+        // entry it's overwriting.
+        self.reclaimable_bytes += 4 + frame_len as u64;
+        if let Some(old) = self.map.remove(key) {
+            self.reclaimable_bytes += release_entry(&old, &mut self.batch_live_counts);
+        }
+        self.order.remove(key);
+
+        self.maybe_auto_compact()
+    }
+
+    /// Returns keys in `[start, end)`, in lexicographic order, paired with
+    /// their values. Walks a bounded range of the key index rather than
+    /// scanning every key, and decodes each value lazily as the iterator is
+    /// advanced. `start >= end` (e.g. a descending range) yields an empty
+    /// iterator instead of panicking.
+    pub fn scan<'a>(&'a self, start: &str, end: &str) -> impl Iterator<Item = Result<(String, Value), KVerror>> + 'a {
+        // `BTreeSet::range` panics if given a start past its end, so collapse
+        // an inverted range to an empty one (`end..end`) up front.
+        let range = if start >= end {
+            end.to_string()..end.to_string()
+        } else {
+            start.to_string()..end.to_string()
+        };
+        self.order.range(range).map(move |key| {
+            let value = self.get(key)?;
+            Ok((key.clone(), value))
+        })
+    }
+
+    /// Returns all keys starting with `prefix`, in lexicographic order,
+    /// paired with their values. Like `scan`, this is a bounded range walk:
+    /// the prefix's upper bound is computed once up front instead of
+    /// filtering every key in the store.
+    pub fn scan_prefix<'a>(&'a self, prefix: &str) -> Box<dyn Iterator<Item = Result<(String, Value), KVerror>> + 'a> {
+        let start = prefix.to_string();
+        match prefix_upper_bound(prefix) {
+            Some(end) => Box::new(self.order.range(start..end).map(move |key| {
+                let value = self.get(key)?;
+                Ok((key.clone(), value))
+            })),
+            None => Box::new(
+                self.order
+                    .range(start.clone()..)
+                    .take_while(move |key| key.starts_with(start.as_str()))
+                    .map(move |key| {
+                        let value = self.get(key)?;
+                        Ok((key.clone(), value))
+                    }),
+            ),
+        }
+    }
+
+    /// Applies a `Batch` atomically: every `check` must pass against the
+    /// current version (or absence) of its key, or nothing is written and
+    /// `KVerror::CheckFailed` is returned. Otherwise all mutations are
+    /// serialized into a single batch frame and appended with one write.
+    pub fn commit(&mut self, batch: Batch) -> Result<(), KVerror> {
+        for (key, expected) in &batch.checks {
+            let current = self.map.get(key).map(|e| e.version);
+            let satisfied = match expected {
+                Some(version) => current == Some(*version),
+                None => current.is_none(),
+            };
+            if !satisfied {
+                return Err(KVerror::CheckFailed);
+            }
+        }
+
+        // 0 stands for "absent" here, since real versions start at 1; it
+        // lets a del-then-set on the same key within one batch restart at 1.
+        let mut projected_versions: HashMap<String, u64> = HashMap::new();
+        let mut value_bytes = Vec::new();
+        value_bytes
+            .write_u32::<LittleEndian>(batch.mutations.len() as u32)
+            .unwrap();
+
+        for mutation in &batch.mutations {
+            match mutation {
+                Mutation::Set(key, value) => {
+                    let current = projected_versions
+                        .get(key)
+                        .copied()
+                        .or_else(|| self.map.get(key).map(|e| e.version));
+                    let next_version = current.map_or(1, |v| v + 1);
+                    projected_versions.insert(key.clone(), next_version);
+
+                    let frame = Frame::new_set(key, value.clone(), self.magic, self.version, next_version);
+                    value_bytes.extend_from_slice(&serialize(&frame, None)?);
+                }
+                Mutation::Del(key) => {
+                    projected_versions.insert(key.clone(), 0);
+                    let frame = Frame::new_delete(key, self.magic, self.version);
+                    value_bytes.extend_from_slice(&serialize(&frame, None)?);
+                }
+            }
+        }
+
+        let batch_frame = Frame::new_batch(value_bytes, self.magic, self.version);
+        let serialized = serialize(&batch_frame, self.cipher.as_ref())?;
+        let frame_total_len = serialized.len() as u32 - 4;
+
+        let offset = self.current_len()?;
+        self.append(serialized)?; // append first to maintain consistency
+
+        let reclaimed = apply_batch(
+            &mut self.map,
+            &mut self.order,
+            &mut self.batch_live_counts,
+            offset,
+            frame_total_len,
+            &batch_frame.value_bytes,
+        )?;
+        self.total_frames += 1;
+        self.reclaimable_bytes += reclaimed;
+
+        self.maybe_auto_compact()
+    }
+
+    /// Returns how much of the log is live versus overwritten/deleted, so a
+    /// caller can decide whether `compact` is worth running.
+    pub fn stats(&self) -> LogStats {
+        LogStats {
+            total_frames: self.total_frames,
+            live_keys: self.map.len() as u64,
+            reclaimable_bytes: self.reclaimable_bytes,
+        }
+    }
+
+    pub fn storage_type(&self) -> StorageType {
+        self.storage_type
+    }
+
+    /// Sets the fraction of the log that must be reclaimable (dead bytes /
+    /// total file size) before `set`/`del`/`commit` trigger a `compact`
+    /// automatically. `None` (the default) disables auto-compaction.
+    pub fn set_auto_compact_threshold(&mut self, threshold: Option<f64>) {
+        self.auto_compact_threshold = threshold;
+    }
+
+    fn maybe_auto_compact(&mut self) -> Result<(), KVerror> {
+        let Some(threshold) = self.auto_compact_threshold else {
+            return Ok(());
+        };
+        let file_len = self.current_len()? as f64;
+        if file_len > 0.0 && self.reclaimable_bytes as f64 / file_len >= threshold {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    fn header_len(&self) -> u64 {
+        if self.cipher.is_some() {
+            ENCRYPTED_HEADER_LEN as u64
+        } else {
+            PLAIN_HEADER_LEN as u64
+        }
+    }
+
+    /// Rewrites the log to contain exactly one set frame per live key (at
+    /// its current version, so outstanding CAS checks still line up),
+    /// dropping every overwritten version and every tombstone.
+    ///
+    /// The rewrite is built up entirely in a temp file, which is `fsync`ed
+    /// and then renamed over the real log; a crash at any point before the
+    /// rename leaves the original log untouched, so compaction is safe to
+    /// retry after a crash.
+    pub fn compact(&mut self) -> Result<(), KVerror> {
+        let tmp_path = format!("{}.compact-tmp", self.log);
+        let mut tmp_file = File::create(&tmp_path).map_err(|_| KVerror::IO)?;
+
+        match (&self.cipher, &self.salt) {
+            (Some(cipher), Some(salt)) => {
+                let header = LogHeader::new(self.magic, self.version, cipher.encryption_type, StorageType::Compacted, *salt);
+                tmp_file.write_all(&header.to_bytes()).map_err(|_| KVerror::IO)?;
+            }
+            _ => {
+                tmp_file
+                    .write_all(&[StorageType::Compacted as u8])
+                    .map_err(|_| KVerror::IO)?;
+            }
+        }
+
+        let mut new_map = HashMap::with_capacity(self.map.len());
+        let mut new_order = BTreeSet::new();
+        let mut offset = self.header_len();
+
+        for key in self.order.iter().cloned().collect::<Vec<_>>() {
+            let entry = *self.map.get(&key).expect("order and map stay in sync");
+            let value = self.read_at(&entry)?;
+            let encoding = value.encoding;
+
+            let frame = Frame::new_set(&key, value, self.magic, self.version, entry.version);
+            let serialized = serialize(&frame, self.cipher.as_ref())?;
+            let frame_len = serialized.len() as u32 - 4;
+            tmp_file.write_all(&serialized).map_err(|_| KVerror::IO)?;
+
+            new_map.insert(
+                key.clone(),
+                IndexEntry { offset, len: frame_len, encoding, version: entry.version, nested: None },
+            );
+            new_order.insert(key);
+            offset += 4 + frame_len as u64;
+        }
+
+        tmp_file.sync_all().map_err(|_| KVerror::IO)?;
+        drop(tmp_file);
+        std::fs::rename(&tmp_path, &self.log).map_err(|_| KVerror::IO)?;
+
+        self.data_file = File::open(&self.log).map_err(|_| KVerror::IO)?;
+        self.map = new_map;
+        self.order = new_order;
+        self.storage_type = StorageType::Compacted;
+        self.total_frames = self.map.len() as u64;
+        self.reclaimable_bytes = 0;
+        self.batch_live_counts.clear();
+
         Ok(())
     }
 }
 
+// Bytes this entry's frame occupies on disk: the whole frame (length prefix
+// included) for a standalone frame, or just this key's own slice for one
+// nested inside a batch frame (the rest of that batch frame may still be
+// live for other keys).
+fn entry_size(entry: &IndexEntry) -> u64 {
+    match entry.nested {
+        Some((_, len)) => len as u64,
+        None => 4 + entry.len as u64,
+    }
+}
+
+// Bytes a batch frame occupies beyond its nested mutations' own slices: the
+// outer 4-byte length prefix, the 20-byte frame header, the 4-byte mutation
+// count `commit` writes at the start of `value_bytes`, and the 4-byte
+// checksum trailer.
+const BATCH_FRAME_OVERHEAD: u64 = 4 + (1 + 1 + 1 + 1 + 4 + 4 + 8) + 4 + 4;
+
+// Frees the bytes `old` occupied on disk, returning how many bytes just
+// became reclaimable. `batch_live_counts` tracks, per batch frame offset,
+// how many of its nested keys are still live; when `old` was the last live
+// key nested in its batch frame, this also credits that frame's own
+// `BATCH_FRAME_OVERHEAD`, which isn't any single key's slice to reclaim.
+fn release_entry(old: &IndexEntry, batch_live_counts: &mut HashMap<u64, u32>) -> u64 {
+    let mut reclaimed = entry_size(old);
+    if old.nested.is_some() {
+        if let Entry::Occupied(mut remaining) = batch_live_counts.entry(old.offset) {
+            *remaining.get_mut() -= 1;
+            if *remaining.get() == 0 {
+                remaining.remove();
+                reclaimed += BATCH_FRAME_OVERHEAD;
+            }
+        }
+    }
+    reclaimed
+}
+
+// Registers one more live key nested in the batch frame at `frame_offset`.
+fn register_nested(batch_live_counts: &mut HashMap<u64, u32>, frame_offset: u64) {
+    *batch_live_counts.entry(frame_offset).or_insert(0) += 1;
+}
+
+// ------------------- Batch -------------------
+
+enum Mutation {
+    Set(String, Value),
+    Del(String),
+}
+
+/// Accumulates a set of checks and mutations to apply atomically via
+/// `KVstore::commit`.
+pub struct Batch {
+    checks: Vec<(String, Option<u64>)>,
+    mutations: Vec<Mutation>,
+}
+
+impl Batch {
+    pub fn new() -> Batch {
+        Batch {
+            checks: Vec::new(),
+            mutations: Vec::new(),
+        }
+    }
+
+    /// Requires `key` to be at `expected_version` (or, with `None`, to be
+    /// absent) for the batch to commit.
+    pub fn check(mut self, key: &str, expected_version: Option<u64>) -> Batch {
+        self.checks.push((key.to_string(), expected_version));
+        self
+    }
+
+    pub fn set(mut self, key: &str, value: Value) -> Batch {
+        self.mutations.push(Mutation::Set(key.to_string(), value));
+        self
+    }
+
+    pub fn del(mut self, key: &str) -> Batch {
+        self.mutations.push(Mutation::Del(key.to_string()));
+        self
+    }
+}
+
+impl Default for Batch {
+    fn default() -> Batch {
+        Batch::new()
+    }
+}
+
+// Applies a decoded batch payload (mutation count + concatenated mutation
+// frames) to the index, pointing each set key at its slice of the batch
+// frame rather than a standalone frame. Shared by replay and by `commit`
+// itself so both paths agree on the on-disk layout.
+// Returns how many bytes of previously-live entries this batch just
+// overwrote or deleted, for the caller to fold into its reclaimable-bytes
+// tally; this includes the batch frame's own `BATCH_FRAME_OVERHEAD` once
+// every key nested in it becomes dead (see `release_entry`).
+fn apply_batch(
+    map: &mut HashMap<String, IndexEntry>,
+    order: &mut BTreeSet<String>,
+    batch_live_counts: &mut HashMap<u64, u32>,
+    frame_offset: u64,
+    frame_total_len: u32,
+    value_bytes: &[u8],
+) -> Result<u64, KVerror> {
+    if value_bytes.len() < 4 {
+        return Err(KVerror::CorruptLog);
+    }
+    let mutation_count = u32::from_le_bytes(value_bytes[0..4].try_into().unwrap());
+
+    let mut pos = 4usize;
+    let mut applied = 0u32;
+    let mut reclaimed = 0u64;
+    while applied < mutation_count {
+        if pos + 4 > value_bytes.len() {
+            return Err(KVerror::CorruptLog);
+        }
+        let mutation_len = u32::from_le_bytes(value_bytes[pos..pos + 4].try_into().unwrap());
+        let body_start = pos + 4;
+        let body_end = body_start + mutation_len as usize;
+        if body_end > value_bytes.len() {
+            return Err(KVerror::CorruptLog);
+        }
+
+        let mutation = deserialize(mutation_len, value_bytes[body_start..body_end].to_vec(), None)?;
+        match mutation.operation {
+            0x01 => {
+                let key = String::from_utf8(mutation.key_bytes).map_err(|_| KVerror::CorruptLog)?;
+                let encoding = Encoding::from_u8(mutation.encoding)?;
+                if let Some(old) = map.get(&key) {
+                    reclaimed += release_entry(old, batch_live_counts);
+                }
+                order.insert(key.clone());
+                map.insert(
+                    key,
+                    IndexEntry {
+                        offset: frame_offset,
+                        len: frame_total_len,
+                        encoding,
+                        version: mutation.key_version,
+                        nested: Some((pos as u32, (body_end - pos) as u32)),
+                    },
+                );
+                register_nested(batch_live_counts, frame_offset);
+            }
+            0x02 => {
+                let key = String::from_utf8(mutation.key_bytes).map_err(|_| KVerror::CorruptLog)?;
+                if let Some(old) = map.remove(&key) {
+                    reclaimed += release_entry(&old, batch_live_counts);
+                }
+                order.remove(&key);
+            }
+            _ => return Err(KVerror::CorruptLog),
+        }
+
+        pos = body_end;
+        applied += 1;
+    }
+
+    Ok(reclaimed)
+}
+
+// ------------------- Order-Preserving Key Encoding -------------------
+
+// A typed component of a composite key built with `encode_key`.
+pub enum KeyPart<'a> {
+    Str(&'a str),
+    Int(i64),
+}
+
+/// Encodes `parts` into a single string whose byte order matches the
+/// logical order of the parts, for keys that need range scans over mixed
+/// string/integer components (plain string keys already sort correctly as
+/// bytes and don't need this).
+///
+/// Each part is hex-encoded, which preserves byte order while keeping the
+/// result valid UTF-8, and parts are joined with a NUL separator so a short
+/// part followed by more parts never collides with a longer part sharing
+/// the same prefix. Integers are stored big-endian with the sign bit
+/// flipped, so negative values sort before positive ones.
+pub fn encode_key(parts: &[KeyPart]) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            KeyPart::Str(s) => hex_encode(s.as_bytes()),
+            KeyPart::Int(n) => {
+                let flipped = (*n as u64) ^ (1u64 << 63);
+                hex_encode(&flipped.to_be_bytes())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\u{0}")
+}
+
+fn hex_encode(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(out, "{:02x}", b).unwrap();
+    }
+    out
+}
+
+// Exclusive upper bound for a lexicographic prefix scan: the prefix with its
+// trailing byte incremented (carrying into prior bytes on overflow). `None`
+// means no such bound exists as a string (e.g. an empty prefix, or one whose
+// incremented bytes aren't valid UTF-8), so the caller should scan forward
+// from the prefix for as long as keys still start with it.
+fn prefix_upper_bound(prefix: &str) -> Option<String> {
+    let mut bytes = prefix.as_bytes().to_vec();
+    while let Some(&last) = bytes.last() {
+        if last < 0xff {
+            *bytes.last_mut().unwrap() += 1;
+            return String::from_utf8(bytes).ok();
+        }
+        bytes.pop();
+    }
+    None
+}
+
 // ------------------- Frame Helpers -------------------
 
 impl Frame {
-    pub fn new_set(key: &str, value: Value, magic: u8, version: u8) -> Frame {
+    pub fn new_set(key: &str, value: Value, magic: u8, version: u8, key_version: u64) -> Frame {
         let key_bytes = key.as_bytes().to_vec();
         let value_bytes = value.bytes.clone();
         let key_len = key_bytes.len() as u32;
         let value_len = value_bytes.len() as u32;
 
         Frame {
-            total_len: 0,
             magic,
             version,
             operation: 0x01,
             encoding: value.encoding as u8,
             key_len,
             value_len,
+            key_version,
             key_bytes,
             value_bytes,
         }
@@ -165,35 +1069,75 @@ impl Frame {
         let key_len = key_bytes.len() as u32;
 
         Frame {
-            total_len: 0,
             magic,
             version,
             operation: 0x02,
             encoding: 0x00,
             key_len,
             value_len: 0,
+            key_version: 0,
             key_bytes,
             value_bytes: vec![],
         }
     }
+
+    // Wraps an already-serialized run of mutation frames (see `apply_batch`)
+    // so they're appended and replayed as a single atomic log record.
+    pub fn new_batch(value_bytes: Vec<u8>, magic: u8, version: u8) -> Frame {
+        let value_len = value_bytes.len() as u32;
+
+        Frame {
+            magic,
+            version,
+            operation: 0x03,
+            encoding: 0x00,
+            key_len: 0,
+            value_len,
+            key_version: 0,
+            key_bytes: vec![],
+            value_bytes,
+        }
+    }
 }
 
 // ------------------- Build Store from Log -------------------
 
-fn build_kv_store(
-    log_file: File,
-    log_path: &str,
+// Store-open-time config that doesn't vary per frame, bundled so adding a
+// new open-time axis doesn't grow `build_kv_store`'s argument list.
+struct OpenConfig {
     magic: u8,
     version: u8,
+    cipher: Option<Cipher>,
+    salt: Option<[u8; 16]>,
+    storage_type: StorageType,
+    header_len: u64,
+}
+
+fn build_kv_store(
+    mut reader: BufReader<File>,
+    log_path: &str,
+    config: OpenConfig,
 ) -> Result<KVstore, KVerror> {
+    let data_file = File::open(log_path).map_err(|_| KVerror::IO)?;
     let mut store = KVstore {
         map: HashMap::new(),
+        order: BTreeSet::new(),
         log: log_path.to_string(),
-        magic,
-        version,
+        magic: config.magic,
+        version: config.version,
+        cipher: config.cipher,
+        salt: config.salt,
+        storage_type: config.storage_type,
+        data_file,
+        total_frames: 0,
+        reclaimable_bytes: 0,
+        auto_compact_threshold: None,
+        batch_live_counts: HashMap::new(),
     };
 
-    let mut reader = BufReader::new(log_file);
+    // Frames are prefixed by their 4-byte length, so the next frame's offset
+    // is this one's offset plus that prefix plus total_len.
+    let mut offset: u64 = config.header_len;
 
     loop {
         // First step is to get the length bytes (u32)
@@ -202,32 +1146,63 @@ fn build_kv_store(
             break; // EOF
         }
         let total_len = u32::from_le_bytes(len_buf);
+        let frame_offset = offset;
 
         let mut frame_buf = vec![0u8; total_len as usize];
-        reader
-            .read_exact(&mut frame_buf)
-            .map_err(|_| KVerror::CorruptLog)?;
+        if reader.read_exact(&mut frame_buf).is_err() {
+            // The length prefix was written but the body wasn't fully
+            // flushed before a crash; the last record never committed, so
+            // stop replaying instead of failing the whole log (this also
+            // makes a torn batch frame discard atomically, since its
+            // mutations never get applied).
+            break;
+        }
 
-        let frame = deserialize(total_len, frame_buf)?;
+        let frame = deserialize(total_len, frame_buf, store.cipher.as_ref())?;
 
+        store.total_frames += 1;
         match frame.operation {
             0x01 => {
                 let key = String::from_utf8(frame.key_bytes).map_err(|_| KVerror::CorruptLog)?;
                 let encoding = Encoding::from_u8(frame.encoding)?;
+                if let Some(old) = store.map.get(&key) {
+                    store.reclaimable_bytes += release_entry(old, &mut store.batch_live_counts);
+                }
+                store.order.insert(key.clone());
                 store.map.insert(
                     key,
-                    Value {
+                    IndexEntry {
+                        offset: frame_offset,
+                        len: total_len,
                         encoding,
-                        bytes: frame.value_bytes,
+                        version: frame.key_version,
+                        nested: None,
                     },
                 );
             }
             0x02 => {
                 let key = String::from_utf8(frame.key_bytes).map_err(|_| KVerror::CorruptLog)?;
-                store.map.remove(&key);
+                store.reclaimable_bytes += 4 + total_len as u64;
+                if let Some(old) = store.map.remove(&key) {
+                    store.reclaimable_bytes += release_entry(&old, &mut store.batch_live_counts);
+                }
+                store.order.remove(&key);
+            }
+            0x03 => {
+                let reclaimed = apply_batch(
+                    &mut store.map,
+                    &mut store.order,
+                    &mut store.batch_live_counts,
+                    frame_offset,
+                    total_len,
+                    &frame.value_bytes,
+                )?;
+                store.reclaimable_bytes += reclaimed;
             }
             _ => return Err(KVerror::CorruptLog),
         }
+
+        offset += 4 + total_len as u64;
     }
 
     Ok(store)
@@ -235,24 +1210,83 @@ fn build_kv_store(
 
 // ------------------- Checksum -------------------
 
-fn compute_checksum(frame: &Frame) -> u32 {
+// The fixed frame-header fields, bundled into one struct so `checksum_over`
+// and `frame_aad` take one argument apiece instead of growing another
+// positional `u8`/`u32` every time a new header field is added.
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
+    magic: u8,
+    version: u8,
+    operation: u8,
+    encoding: u8,
+    key_len: u32,
+    value_len: u32,
+    key_version: u64,
+}
+
+impl FrameHeader {
+    fn of(frame: &Frame) -> FrameHeader {
+        FrameHeader {
+            magic: frame.magic,
+            version: frame.version,
+            operation: frame.operation,
+            encoding: frame.encoding,
+            key_len: frame.key_len,
+            value_len: frame.value_len,
+            key_version: frame.key_version,
+        }
+    }
+}
+
+// Hashes the frame header fields plus whatever region bytes are on disk for
+// this frame (plaintext key+value, or the encrypted nonce||ciphertext||tag
+// blob), so corruption is caught whether or not the log is encrypted.
+fn checksum_over(header: &FrameHeader, regions: &[&[u8]]) -> u32 {
     let mut hasher = crc32fast::Hasher::new();
-    hasher.update(&[frame.magic]);
-    hasher.update(&[frame.version]);
-    hasher.update(&[frame.operation]);
-    hasher.update(&[frame.encoding]);
-    hasher.update(&frame.key_len.to_le_bytes());
-    hasher.update(&frame.value_len.to_le_bytes());
-    hasher.update(&frame.key_bytes);
-    hasher.update(&frame.value_bytes);
+    hasher.update(&[header.magic]);
+    hasher.update(&[header.version]);
+    hasher.update(&[header.operation]);
+    hasher.update(&[header.encoding]);
+    hasher.update(&header.key_len.to_le_bytes());
+    hasher.update(&header.value_len.to_le_bytes());
+    hasher.update(&header.key_version.to_le_bytes());
+    for region in regions {
+        hasher.update(region);
+    }
     hasher.finalize()
 }
 
+fn compute_checksum(frame: &Frame) -> u32 {
+    checksum_over(&FrameHeader::of(frame), &[&frame.key_bytes, &frame.value_bytes])
+}
+
+// AEAD associated data: the frame header fields, so tampering with
+// operation/encoding/lengths/key_version is detected even though they're
+// never encrypted.
+fn frame_aad(header: &FrameHeader) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(1 + 1 + 1 + 1 + 4 + 4 + 8);
+    aad.push(header.magic);
+    aad.push(header.version);
+    aad.push(header.operation);
+    aad.push(header.encoding);
+    aad.extend_from_slice(&header.key_len.to_le_bytes());
+    aad.extend_from_slice(&header.value_len.to_le_bytes());
+    aad.extend_from_slice(&header.key_version.to_le_bytes());
+    aad
+}
+
 // ------------------- Serialize -------------------
 
-fn serialize(frame: Frame) -> Vec<u8> {
-    let checksum = compute_checksum(&frame);
-    let total_len: u32 = 1 + 1 + 1 + 1 + 4 + 4 + frame.key_len + frame.value_len + 4;
+fn serialize(frame: &Frame, cipher: Option<&Cipher>) -> Result<Vec<u8>, KVerror> {
+    match cipher {
+        None => Ok(serialize_plain(frame)),
+        Some(cipher) => serialize_encrypted(frame, cipher),
+    }
+}
+
+fn serialize_plain(frame: &Frame) -> Vec<u8> {
+    let checksum = compute_checksum(frame);
+    let total_len: u32 = 1 + 1 + 1 + 1 + 4 + 4 + 8 + frame.key_len + frame.value_len + 4;
 
     let mut buffer = vec![];
     buffer.write_u32::<LittleEndian>(total_len).unwrap();
@@ -262,6 +1296,7 @@ fn serialize(frame: Frame) -> Vec<u8> {
     buffer.write_u8(frame.encoding).unwrap();
     buffer.write_u32::<LittleEndian>(frame.key_len).unwrap();
     buffer.write_u32::<LittleEndian>(frame.value_len).unwrap();
+    buffer.write_u64::<LittleEndian>(frame.key_version).unwrap();
     buffer.write_all(&frame.key_bytes).unwrap();
     buffer.write_all(&frame.value_bytes).unwrap();
     buffer.write_u32::<LittleEndian>(checksum).unwrap();
@@ -269,9 +1304,44 @@ fn serialize(frame: Frame) -> Vec<u8> {
     buffer
 }
 
+fn serialize_encrypted(frame: &Frame, cipher: &Cipher) -> Result<Vec<u8>, KVerror> {
+    let header = FrameHeader::of(frame);
+    let aad = frame_aad(&header);
+
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce_bytes);
+
+    let mut plaintext = Vec::with_capacity(frame.key_bytes.len() + frame.value_bytes.len());
+    plaintext.extend_from_slice(&frame.key_bytes);
+    plaintext.extend_from_slice(&frame.value_bytes);
+
+    let ciphertext = cipher.encrypt(&nonce_bytes, &aad, &plaintext)?;
+
+    let mut payload = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+    payload.extend_from_slice(&nonce_bytes);
+    payload.extend_from_slice(&ciphertext);
+
+    let checksum = checksum_over(&header, &[&payload]);
+    let total_len: u32 = 1 + 1 + 1 + 1 + 4 + 4 + 8 + payload.len() as u32 + 4;
+
+    let mut buffer = vec![];
+    buffer.write_u32::<LittleEndian>(total_len).unwrap();
+    buffer.write_u8(frame.magic).unwrap();
+    buffer.write_u8(frame.version).unwrap();
+    buffer.write_u8(frame.operation).unwrap();
+    buffer.write_u8(frame.encoding).unwrap();
+    buffer.write_u32::<LittleEndian>(frame.key_len).unwrap();
+    buffer.write_u32::<LittleEndian>(frame.value_len).unwrap();
+    buffer.write_u64::<LittleEndian>(frame.key_version).unwrap();
+    buffer.write_all(&payload).unwrap();
+    buffer.write_u32::<LittleEndian>(checksum).unwrap();
+
+    Ok(buffer)
+}
+
 // ------------------- Deserialize -------------------
 
-fn deserialize(total_len: u32, mut bytes: Vec<u8>) -> Result<Frame, KVerror> {
+fn deserialize(total_len: u32, mut bytes: Vec<u8>, cipher: Option<&Cipher>) -> Result<Frame, KVerror> {
     let mut cursor = Cursor::new(&mut bytes);
 
     let magic = cursor.read_u8().map_err(|_| KVerror::CorruptLog)?;
@@ -280,42 +1350,99 @@ fn deserialize(total_len: u32, mut bytes: Vec<u8>) -> Result<Frame, KVerror> {
     let encoding = cursor.read_u8().map_err(|_| KVerror::CorruptLog)?;
     let key_len = cursor.read_u32::<LittleEndian>().map_err(|_| KVerror::CorruptLog)?;
     let value_len = cursor.read_u32::<LittleEndian>().map_err(|_| KVerror::CorruptLog)?;
+    let key_version = cursor.read_u64::<LittleEndian>().map_err(|_| KVerror::CorruptLog)?;
 
-    let expected_len = 1 + 1 + 1 + 1 + 4 + 4 + key_len + value_len + 4;
-    if total_len != expected_len {
-        return Err(KVerror::CorruptLog);
-    }
+    match cipher {
+        None => {
+            let expected_len = 1 + 1 + 1 + 1 + 4 + 4 + 8 + key_len + value_len + 4;
+            if total_len != expected_len {
+                return Err(KVerror::CorruptLog);
+            }
 
-    let mut key_bytes = vec![0u8; key_len as usize];
-    cursor
-        .read_exact(&mut key_bytes)
-        .map_err(|_| KVerror::CorruptLog)?;
-
-    let mut value_bytes = vec![0u8; value_len as usize];
-    cursor
-        .read_exact(&mut value_bytes)
-        .map_err(|_| KVerror::CorruptLog)?;
-
-    let original_checksum = cursor
-        .read_u32::<LittleEndian>()
-        .map_err(|_| KVerror::CorruptLog)?;
-
-    let frame = Frame {
-        total_len,
-        magic,
-        version,
-        operation,
-        encoding,
-        key_len,
-        value_len,
-        key_bytes,
-        value_bytes,
-    };
+            let mut key_bytes = vec![0u8; key_len as usize];
+            cursor
+                .read_exact(&mut key_bytes)
+                .map_err(|_| KVerror::CorruptLog)?;
 
-    let computed = compute_checksum(&frame);
-    if computed != original_checksum {
-        return Err(KVerror::CorruptLog);
-    }
+            let mut value_bytes = vec![0u8; value_len as usize];
+            cursor
+                .read_exact(&mut value_bytes)
+                .map_err(|_| KVerror::CorruptLog)?;
+
+            let original_checksum = cursor
+                .read_u32::<LittleEndian>()
+                .map_err(|_| KVerror::CorruptLog)?;
+
+            let frame = Frame {
+                magic,
+                version,
+                operation,
+                encoding,
+                key_len,
+                value_len,
+                key_version,
+                key_bytes,
+                value_bytes,
+            };
+
+            let computed = compute_checksum(&frame);
+            if computed != original_checksum {
+                return Err(KVerror::CorruptLog);
+            }
+
+            Ok(frame)
+        }
+        Some(cipher) => {
+            let payload_len = NONCE_LEN as u32 + key_len + value_len + TAG_LEN as u32;
+            let expected_len = 1 + 1 + 1 + 1 + 4 + 4 + 8 + payload_len + 4;
+            if total_len != expected_len {
+                return Err(KVerror::CorruptLog);
+            }
 
-    Ok(frame)
+            let mut payload = vec![0u8; payload_len as usize];
+            cursor
+                .read_exact(&mut payload)
+                .map_err(|_| KVerror::CorruptLog)?;
+
+            let original_checksum = cursor
+                .read_u32::<LittleEndian>()
+                .map_err(|_| KVerror::CorruptLog)?;
+
+            let header = FrameHeader {
+                magic,
+                version,
+                operation,
+                encoding,
+                key_len,
+                value_len,
+                key_version,
+            };
+
+            // CRC catches bit-rot on the blob; the AEAD tag (checked below)
+            // catches tampering or a wrong passphrase, so the two failure
+            // modes stay distinguishable.
+            let computed = checksum_over(&header, &[&payload]);
+            if computed != original_checksum {
+                return Err(KVerror::CorruptLog);
+            }
+
+            let (nonce_bytes, ciphertext) = payload.split_at(NONCE_LEN);
+            let aad = frame_aad(&header);
+            let plaintext = cipher.decrypt(nonce_bytes, &aad, ciphertext)?;
+
+            let (key_bytes, value_bytes) = plaintext.split_at(key_len as usize);
+
+            Ok(Frame {
+                magic,
+                version,
+                operation,
+                encoding,
+                key_len,
+                value_len,
+                key_version,
+                key_bytes: key_bytes.to_vec(),
+                value_bytes: value_bytes.to_vec(),
+            })
+        }
+    }
 }
@@ -0,0 +1,80 @@
+mod common;
+
+use common::{string_value, TempLog};
+use kv_store::node::{Batch, KVerror, KVstore};
+
+#[test]
+fn commit_enforces_compare_and_set_and_applies_mutations_atomically() {
+    let log = TempLog::new("commit-cas");
+    let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    store.set("balance", string_value("100")).unwrap();
+    let version = 1u64; // first `set` on a new key always starts its version at 1
+
+    let stale = Batch::new()
+        .check("balance", Some(version + 1))
+        .set("balance", string_value("90"));
+    assert!(matches!(store.commit(stale), Err(KVerror::CheckFailed)));
+    // A failed check must not write anything.
+    assert_eq!(store.get("balance").unwrap().bytes, b"100");
+
+    let ok = Batch::new()
+        .check("balance", Some(version))
+        .set("balance", string_value("90"))
+        .set("ledger", string_value("-10"));
+    store.commit(ok).unwrap();
+    assert_eq!(store.get("balance").unwrap().bytes, b"90");
+    assert_eq!(store.get("ledger").unwrap().bytes, b"-10");
+
+    let with_delete = Batch::new().del("ledger");
+    store.commit(with_delete).unwrap();
+    assert!(matches!(store.get("ledger"), Err(KVerror::NotFound)));
+}
+
+#[test]
+fn reclaimable_bytes_accounts_for_batch_frame_overhead_once_all_its_keys_are_dead() {
+    let log = TempLog::new("batch-overhead");
+    let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+
+    let batch = Batch::new().set("a", string_value("1")).set("b", string_value("2"));
+    store.commit(batch).unwrap();
+    assert_eq!(store.stats().reclaimable_bytes, 0);
+
+    // Kill both keys the batch frame wrote -- one via overwrite, one via
+    // delete -- so nothing in the original batch frame survives.
+    store.set("a", string_value("1-updated")).unwrap();
+    store.del("b").unwrap();
+
+    let before = std::fs::metadata(log.path()).unwrap().len();
+    let reclaimable = store.stats().reclaimable_bytes;
+    store.compact().unwrap();
+    let after = std::fs::metadata(log.path()).unwrap().len();
+
+    // `reclaimable_bytes` must predict exactly how many bytes `compact`
+    // actually drops, including the dead batch frame's own length
+    // prefix/header/mutation-count/checksum overhead -- not just the two
+    // mutations' own slices.
+    assert_eq!(before - after, reclaimable);
+}
+
+#[test]
+fn torn_batch_tail_is_discarded_on_replay() {
+    let log = TempLog::new("torn-batch");
+    {
+        let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+        store.set("x", string_value("1")).unwrap();
+        let batch = Batch::new().set("y", string_value("2")).set("z", string_value("3"));
+        store.commit(batch).unwrap();
+    }
+
+    // Truncate the file to cut off the tail of the batch frame, as if the
+    // process crashed mid-`write_all`.
+    let full_len = std::fs::metadata(log.path()).unwrap().len();
+    let file = std::fs::OpenOptions::new().write(true).open(log.path()).unwrap();
+    file.set_len(full_len - 4).unwrap();
+    drop(file);
+
+    let store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    assert_eq!(store.get("x").unwrap().bytes, b"1"); // earlier, complete frame survives
+    assert!(matches!(store.get("y"), Err(KVerror::NotFound)));
+    assert!(matches!(store.get("z"), Err(KVerror::NotFound)));
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use common::{string_value, TempLog};
+use kv_store::node::{KVerror, KVstore};
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[test]
+fn get_many_and_set_many_bulk_operations() {
+    let log = TempLog::new("bulk");
+    let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    store
+        .set_many(vec![
+            ("a".to_string(), string_value("1")),
+            ("b".to_string(), string_value("2")),
+        ])
+        .unwrap();
+
+    let found = store.get_many(&["a", "b", "missing"]).unwrap();
+    assert_eq!(found.len(), 2);
+    assert_eq!(found["a"].bytes, b"1");
+    assert_eq!(found["b"].bytes, b"2");
+}
+
+#[test]
+fn get_many_propagates_errors_other_than_not_found() {
+    let log = TempLog::new("bulk-corrupt");
+    let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    store.set("good", string_value("fine")).unwrap();
+    store.set("bad", string_value("corrupt-me")).unwrap();
+
+    // `get`/`get_many` read a key's frame lazily at its recorded offset, so
+    // corrupting the on-disk frame after the store is already open (without
+    // reopening) only affects reads of that one key, and only once it's
+    // actually looked up.
+    let mut file = OpenOptions::new().read(true).write(true).open(log.path()).unwrap();
+    let end = std::fs::metadata(log.path()).unwrap().len();
+    file.seek(SeekFrom::Start(end - 8)).unwrap();
+    let mut byte = [0u8; 1];
+    file.read_exact(&mut byte).unwrap();
+    byte[0] ^= 0xFF;
+    file.seek(SeekFrom::Start(end - 8)).unwrap();
+    file.write_all(&byte).unwrap();
+    drop(file);
+
+    assert_eq!(store.get("good").unwrap().bytes, b"fine");
+    assert!(matches!(store.get("bad"), Err(KVerror::CorruptLog)));
+
+    let result = store.get_many(&["good", "bad"]);
+    assert!(matches!(result, Err(KVerror::CorruptLog)));
+}
@@ -0,0 +1,25 @@
+mod common;
+
+use common::{string_value, TempLog};
+use kv_store::node::{KVerror, KVstore, Value};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Account {
+    name: String,
+    balance: i64,
+}
+
+#[test]
+fn cbor_value_roundtrip_and_decode_mismatch() {
+    let log = TempLog::new("cbor");
+    let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    let account = Account { name: "ada".to_string(), balance: 42 };
+    store.set("account", Value::from_cbor(&account).unwrap()).unwrap();
+
+    let decoded: Account = store.get_as("account").unwrap();
+    assert_eq!(decoded, account);
+
+    store.set("plain", string_value("not cbor")).unwrap();
+    assert!(matches!(store.get_as::<Account>("plain"), Err(KVerror::Encoding)));
+}
@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+// Every test gets its own log path under the system temp dir, cleaned up
+// (log + any leftover `.compact-tmp`) when the guard drops, so tests can run
+// concurrently without clobbering each other or leaving files behind.
+pub struct TempLog(pub PathBuf);
+
+impl TempLog {
+    pub fn new(name: &str) -> TempLog {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        TempLog(std::env::temp_dir().join(format!("kvstore-test-{name}-{nanos}.log")))
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+impl Drop for TempLog {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+        let _ = std::fs::remove_file(format!("{}.compact-tmp", self.0.display()));
+    }
+}
+
+pub fn string_value(s: &str) -> kv_store::node::Value {
+    kv_store::node::Value {
+        encoding: kv_store::node::Encoding::String,
+        bytes: s.as_bytes().to_vec(),
+    }
+}
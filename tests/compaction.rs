@@ -0,0 +1,64 @@
+mod common;
+
+use common::{string_value, TempLog};
+use kv_store::node::{KVerror, KVstore, StorageType};
+
+#[test]
+fn compact_reclaims_dead_space_but_keeps_live_values_intact() {
+    let log = TempLog::new("compact");
+    let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    store.set("a", string_value("1")).unwrap();
+    store.set("a", string_value("2")).unwrap(); // overwritten, now dead
+    store.set("b", string_value("3")).unwrap();
+    store.del("b").unwrap(); // tombstoned, now dead
+
+    let before = store.stats();
+    assert_eq!(before.total_frames, 4);
+    assert!(before.reclaimable_bytes > 0);
+    store.compact().unwrap();
+
+    let after = store.stats();
+    assert_eq!(after.reclaimable_bytes, 0);
+    assert_eq!(after.total_frames, 1);
+    assert_eq!(after.live_keys, 1);
+    assert_eq!(store.storage_type(), StorageType::Compacted);
+    assert_eq!(store.get("a").unwrap().bytes, b"2");
+    assert!(matches!(store.get("b"), Err(KVerror::NotFound)));
+}
+
+#[test]
+fn auto_compact_threshold_triggers_compaction_on_write() {
+    let log = TempLog::new("auto-compact");
+    let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    store.set_auto_compact_threshold(Some(0.5));
+
+    for _ in 0..10 {
+        store.set("hot", string_value("churning")).unwrap();
+    }
+
+    // Overwriting the same key repeatedly should have crossed the 50%
+    // dead-bytes threshold at least once and triggered a compaction
+    // automatically, without the caller ever calling `compact` itself.
+    assert_eq!(store.storage_type(), StorageType::Compacted);
+    assert_eq!(store.get("hot").unwrap().bytes, b"churning");
+}
+
+#[test]
+fn a_crash_partway_through_compaction_leaves_the_original_log_intact() {
+    let log = TempLog::new("compact-crash");
+    let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    store.set("a", string_value("1")).unwrap();
+
+    // Simulate a crash after the temp file is created but before the atomic
+    // rename: leave a half-written temp file next to the log without ever
+    // calling `compact`.
+    let tmp_path = format!("{}.compact-tmp", log.path().display());
+    std::fs::write(&tmp_path, b"garbage-partial-write").unwrap();
+
+    // The original log must still be readable and correct; the stray temp
+    // file is simply overwritten or ignored by a later `compact`.
+    let reopened = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    assert_eq!(reopened.get("a").unwrap().bytes, b"1");
+    drop(store);
+    let _ = std::fs::remove_file(&tmp_path);
+}
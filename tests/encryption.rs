@@ -0,0 +1,102 @@
+mod common;
+
+use common::{string_value, TempLog};
+use kv_store::node::{EncryptionType, KVerror, KVstore};
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+#[test]
+fn open_encrypted_roundtrip_and_wrong_passphrase() {
+    let log = TempLog::new("enc-roundtrip");
+    {
+        let mut store =
+            KVstore::open_encrypted(log.path(), 0xAA, 0x01, "correct horse", EncryptionType::Aes256Gcm).unwrap();
+        store.set("secret", string_value("treasure")).unwrap();
+    }
+
+    let store =
+        KVstore::open_encrypted(log.path(), 0xAA, 0x01, "correct horse", EncryptionType::Aes256Gcm).unwrap();
+    assert_eq!(store.get("secret").unwrap().bytes, b"treasure");
+
+    let wrong_passphrase =
+        KVstore::open_encrypted(log.path(), 0xAA, 0x01, "wrong horse", EncryptionType::Aes256Gcm);
+    assert!(matches!(wrong_passphrase, Err(KVerror::AuthenticationFailed)));
+}
+
+#[test]
+fn open_encrypted_rejects_mismatched_encryption_type_on_reopen() {
+    let log = TempLog::new("enc-mismatch");
+    {
+        let mut store =
+            KVstore::open_encrypted(log.path(), 0xAA, 0x01, "passphrase", EncryptionType::Aes256Gcm).unwrap();
+        store.set("k", string_value("v")).unwrap();
+    }
+
+    let reopened =
+        KVstore::open_encrypted(log.path(), 0xAA, 0x01, "passphrase", EncryptionType::ChaCha20Poly1305);
+    assert!(matches!(reopened, Err(KVerror::EncryptionMismatch)));
+}
+
+// On-disk layout constants from the frame format (header magic/version/kdf_id/
+// encryption_type/storage_type + 16-byte salt, then per-frame nonce/tag sizes).
+const ENCRYPTED_HEADER_LEN: u64 = 1 + 1 + 1 + 1 + 1 + 16;
+const NONCE_LEN: usize = 12;
+
+#[test]
+fn open_encrypted_detects_a_tampered_frame() {
+    let log = TempLog::new("enc-tamper");
+    {
+        let mut store =
+            KVstore::open_encrypted(log.path(), 0xAA, 0x01, "passphrase", EncryptionType::Aes256Gcm).unwrap();
+        store.set("k", string_value("v")).unwrap();
+    }
+
+    // Flip a byte inside the ciphertext (just past the nonce) and patch the
+    // CRC so it still matches the tampered bytes. This isolates the AEAD tag
+    // as the only thing that can catch the tampering, so the failure must
+    // surface as `AuthenticationFailed`, not `CorruptLog`.
+    let mut file = OpenOptions::new().read(true).write(true).open(log.path()).unwrap();
+    file.seek(SeekFrom::Start(ENCRYPTED_HEADER_LEN)).unwrap();
+    let mut len_buf = [0u8; 4];
+    file.read_exact(&mut len_buf).unwrap();
+    let total_len = u32::from_le_bytes(len_buf) as u64;
+
+    let frame_header_len = 1 + 1 + 1 + 1 + 4 + 4 + 8u64;
+    let payload_start = ENCRYPTED_HEADER_LEN + 4 + frame_header_len;
+    let payload_len = total_len - frame_header_len - 4;
+
+    let mut header_fields = [0u8; 20];
+    file.seek(SeekFrom::Start(ENCRYPTED_HEADER_LEN + 4)).unwrap();
+    file.read_exact(&mut header_fields).unwrap();
+    let (magic, version, operation, encoding) =
+        (header_fields[0], header_fields[1], header_fields[2], header_fields[3]);
+    let key_len = u32::from_le_bytes(header_fields[4..8].try_into().unwrap());
+    let value_len = u32::from_le_bytes(header_fields[8..12].try_into().unwrap());
+    let key_version = u64::from_le_bytes(header_fields[12..20].try_into().unwrap());
+
+    let mut payload = vec![0u8; payload_len as usize];
+    file.seek(SeekFrom::Start(payload_start)).unwrap();
+    file.read_exact(&mut payload).unwrap();
+    payload[NONCE_LEN] ^= 0xFF; // corrupt the first ciphertext byte
+
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&[magic]);
+    hasher.update(&[version]);
+    hasher.update(&[operation]);
+    hasher.update(&[encoding]);
+    hasher.update(&key_len.to_le_bytes());
+    hasher.update(&value_len.to_le_bytes());
+    hasher.update(&key_version.to_le_bytes());
+    hasher.update(&payload);
+    let new_checksum = hasher.finalize();
+
+    file.seek(SeekFrom::Start(payload_start)).unwrap();
+    file.write_all(&payload).unwrap();
+    file.write_all(&new_checksum.to_le_bytes()).unwrap();
+    file.flush().unwrap();
+    drop(file);
+
+    let reopened =
+        KVstore::open_encrypted(log.path(), 0xAA, 0x01, "passphrase", EncryptionType::Aes256Gcm);
+    assert!(matches!(reopened, Err(KVerror::AuthenticationFailed)));
+}
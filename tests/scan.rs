@@ -0,0 +1,46 @@
+mod common;
+
+use common::TempLog;
+use kv_store::node::{encode_key, KVstore, KeyPart};
+
+#[test]
+fn scan_and_scan_prefix_return_ordered_keys() {
+    let log = TempLog::new("scan");
+    let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    for key in ["fruit:banana", "fruit:apple", "veggie:carrot", "fruit:cherry"] {
+        store.set(key, common::string_value(key)).unwrap();
+    }
+
+    let range: Vec<String> = store.scan("fruit:", "fruit:z").map(|r| r.unwrap().0).collect();
+    assert_eq!(range, vec!["fruit:apple", "fruit:banana", "fruit:cherry"]);
+
+    let prefix: Vec<String> = store.scan_prefix("fruit:").map(|r| r.unwrap().0).collect();
+    assert_eq!(prefix, vec!["fruit:apple", "fruit:banana", "fruit:cherry"]);
+}
+
+#[test]
+fn scan_with_inverted_range_returns_empty_instead_of_panicking() {
+    let log = TempLog::new("scan-inverted");
+    let mut store = KVstore::open(log.path(), 0xAA, 0x01).unwrap();
+    store.set("a", common::string_value("1")).unwrap();
+
+    let results: Vec<_> = store.scan("z", "a").collect();
+    assert!(results.is_empty());
+
+    let results: Vec<_> = store.scan("a", "a").collect();
+    assert!(results.is_empty());
+}
+
+#[test]
+fn encode_key_orders_negative_integers_before_positive() {
+    let low = encode_key(&[KeyPart::Int(-5)]);
+    let high = encode_key(&[KeyPart::Int(5)]);
+    assert!(low < high);
+}
+
+#[test]
+fn encode_key_orders_string_parts_lexicographically() {
+    let a = encode_key(&[KeyPart::Str("apple")]);
+    let b = encode_key(&[KeyPart::Str("banana")]);
+    assert!(a < b);
+}